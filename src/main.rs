@@ -2,6 +2,7 @@ use warp::Filter;
 
 use clap::Clap;
 use log::info;
+use rand::seq::SliceRandom;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use url::Url;
@@ -21,6 +22,18 @@ struct Request {
     user_name: String,
 }
 
+/// A webhook request that carries its own slash command token in the body,
+/// as Mattermost does with the `token` form field.
+trait HasToken {
+    fn token(&self) -> &str;
+}
+
+impl HasToken for Request {
+    fn token(&self) -> &str {
+        &self.token
+    }
+}
+
 #[derive(Serialize, Debug)]
 struct Response {
     text: Option<String>,
@@ -78,26 +91,66 @@ pub fn token_authorization() -> impl Filter<Extract = (String,), Error = warp::R
         .boxed()
 }
 
+/// Where `webhook` reads the slash command token from.
+#[derive(Debug, Clone, Copy)]
+enum TokenSource {
+    /// Requires an `Authorization: Token <token>` header, typically injected
+    /// by a reverse proxy in front of Mattermost.
+    Header,
+    /// Reads Mattermost's native `token` form field directly, so no reverse
+    /// proxy is needed.
+    Body,
+}
+
+impl std::str::FromStr for TokenSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "header" => Ok(TokenSource::Header),
+            "body" => Ok(TokenSource::Body),
+            other => Err(format!(
+                "invalid token source `{}`, expected `header` or `body`",
+                other
+            )),
+        }
+    }
+}
+
 pub fn webhook<F, T>(
+    token_source: TokenSource,
     token_validator: F,
 ) -> impl Clone + std::fmt::Debug + Filter<Extract = (T,), Error = warp::Rejection>
 where
     F: 'static + Fn(&str) -> bool + Clone + Send + Sync,
-    T: 'static + DeserializeOwned + Send,
+    T: 'static + DeserializeOwned + HasToken + Send,
 {
-    warp::post()
-        .and(token_authorization())
-        .and(warp::body::form())
-        .map(move |authorization: String, request: T| {
-            println!("auth token {}", authorization);
-            if token_validator(&authorization) {
-                Ok(request)
-            } else {
-                Err(Error::InvalidToken)
-            }
-        })
-        .and_then(|result: Result<_, _>| async { result.map_err(problem::build) })
-        .boxed()
+    match token_source {
+        TokenSource::Header => warp::post()
+            .and(token_authorization())
+            .and(warp::body::form())
+            .map(move |authorization: String, request: T| {
+                println!("auth token {}", authorization);
+                if token_validator(&authorization) {
+                    Ok(request)
+                } else {
+                    Err(Error::InvalidToken)
+                }
+            })
+            .and_then(|result: Result<_, _>| async { result.map_err(problem::build) })
+            .boxed(),
+        TokenSource::Body => warp::post()
+            .and(warp::body::form())
+            .map(move |request: T| {
+                if token_validator(request.token()) {
+                    Ok(request)
+                } else {
+                    Err(Error::InvalidToken)
+                }
+            })
+            .and_then(|result: Result<_, _>| async { result.map_err(problem::build) })
+            .boxed(),
+    }
 }
 
 struct MemeRequest {
@@ -105,6 +158,327 @@ struct MemeRequest {
     boxes: Vec<String>,
 }
 
+#[derive(Deserialize, Debug)]
+struct MemeAlias {
+    keyword: String,
+    id: Option<u64>,
+    #[serde(default)]
+    random: bool,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct MemeConfig {
+    #[serde(default)]
+    memes: Vec<MemeAlias>,
+}
+
+impl MemeConfig {
+    fn load(path: &std::path::Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        let config: Self = toml::from_str(&contents)?;
+        for alias in &config.memes {
+            if alias.id.is_none() && !alias.random {
+                anyhow::bail!(
+                    "meme alias `{}` has neither `id` nor `random = true`",
+                    alias.keyword
+                );
+            }
+        }
+        Ok(config)
+    }
+
+    fn resolve(&self, keyword: &str) -> Option<MemeSelection> {
+        let alias = self.memes.iter().find(|alias| alias.keyword == keyword)?;
+        if alias.random {
+            Some(MemeSelection::Random)
+        } else {
+            alias.id.map(|id| MemeSelection::Id(id.to_string()))
+        }
+    }
+}
+
+/// Per-team, persistent meme aliases registered at runtime through the
+/// `alias` subcommands, backed by an embedded sled database.
+///
+/// Each team gets its own sled tree, keyed by `team_id`, so teams are
+/// isolated by sled's exact tree-name lookup rather than by a shared
+/// key-prefix convention (which a `team_id` containing the separator could
+/// otherwise defeat).
+struct AliasStore {
+    db: sled::Db,
+}
+
+impl AliasStore {
+    fn open(path: &std::path::Path) -> sled::Result<Self> {
+        Ok(AliasStore {
+            db: sled::open(path)?,
+        })
+    }
+
+    // `open_tree`/`insert`/`flush` are synchronous and `flush` forces an
+    // fsync, so both run on a blocking thread pool thread instead of a tokio
+    // worker thread.
+    async fn add(&self, team_id: &str, keyword: &str, id: &str) -> sled::Result<()> {
+        let db = self.db.clone();
+        let team_id = team_id.to_string();
+        let keyword = keyword.to_string();
+        let value = id.as_bytes().to_vec();
+        tokio::task::spawn_blocking(move || {
+            let tree = db.open_tree(team_id)?;
+            tree.insert(keyword, value)?;
+            tree.flush()?;
+            Ok(())
+        })
+        .await
+        .expect("alias store add task panicked")
+    }
+
+    async fn remove(&self, team_id: &str, keyword: &str) -> sled::Result<bool> {
+        let db = self.db.clone();
+        let team_id = team_id.to_string();
+        let keyword = keyword.to_string();
+        tokio::task::spawn_blocking(move || {
+            let tree = db.open_tree(team_id)?;
+            let removed = tree.remove(keyword)?.is_some();
+            tree.flush()?;
+            Ok(removed)
+        })
+        .await
+        .expect("alias store remove task panicked")
+    }
+
+    fn resolve(&self, team_id: &str, keyword: &str) -> Option<String> {
+        let tree = self.db.open_tree(team_id).ok()?;
+        tree.get(keyword)
+            .ok()
+            .flatten()
+            .and_then(|value| String::from_utf8(value.to_vec()).ok())
+    }
+
+    fn list(&self, team_id: &str) -> Vec<(String, String)> {
+        let tree = match self.db.open_tree(team_id) {
+            Ok(tree) => tree,
+            Err(_) => return Vec::new(),
+        };
+        tree.iter()
+            .filter_map(|entry| entry.ok())
+            .filter_map(|(key, value)| {
+                let keyword = String::from_utf8(key.to_vec()).ok()?;
+                let id = String::from_utf8(value.to_vec()).ok()?;
+                Some((keyword, id))
+            })
+            .collect()
+    }
+}
+
+enum AliasCommand<'a> {
+    Add { keyword: &'a str, id: &'a str },
+    Del { keyword: &'a str },
+    List,
+}
+
+fn parse_alias_command(first_line: &str) -> Option<AliasCommand> {
+    let mut words = first_line.split_whitespace();
+    if words.next()? != "alias" {
+        return None;
+    }
+    match words.next()? {
+        "add" => Some(AliasCommand::Add {
+            keyword: words.next()?,
+            id: words.next()?,
+        }),
+        "del" => Some(AliasCommand::Del {
+            keyword: words.next()?,
+        }),
+        "list" => Some(AliasCommand::List),
+        _ => None,
+    }
+}
+
+fn alias_response(text: String) -> Response {
+    let alias_response = Response {
+        text: Some(text),
+        response_type: None,
+        username: None,
+        channel_id: None,
+        icon_url: Some(Url::parse("https://imgflip.com/imgflip_white_96.png").unwrap()),
+        goto_location: None,
+        skip_slack_parsing: Some(true),
+    };
+    info!(
+        "alias response {:?}",
+        serde_json::to_string(&alias_response)
+    );
+    alias_response
+}
+
+/// A resolved meme identifier: either a concrete template ID, or a request
+/// to pick uniformly at random from the cached template list.
+enum MemeSelection {
+    Id(String),
+    Random,
+}
+
+const RANDOM_KEYWORD: &str = "random";
+
+#[derive(Clone, Debug)]
+struct Template {
+    id: String,
+    name: String,
+}
+
+const TEMPLATE_CACHE_TTL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+struct TemplateCache {
+    imgflip: std::sync::Arc<imgflip::AccountClient>,
+    state: tokio::sync::RwLock<TemplateCacheState>,
+}
+
+struct TemplateCacheState {
+    templates: Vec<Template>,
+    refreshed_at: Option<std::time::Instant>,
+}
+
+impl TemplateCache {
+    fn new(imgflip: std::sync::Arc<imgflip::AccountClient>) -> Self {
+        TemplateCache {
+            imgflip,
+            state: tokio::sync::RwLock::new(TemplateCacheState {
+                templates: Vec::new(),
+                refreshed_at: None,
+            }),
+        }
+    }
+
+    async fn templates(&self) -> Vec<Template> {
+        {
+            let state = self.state.read().await;
+            if let Some(refreshed_at) = state.refreshed_at {
+                if refreshed_at.elapsed() < TEMPLATE_CACHE_TTL {
+                    return state.templates.clone();
+                }
+            }
+        }
+        self.refresh().await
+    }
+
+    async fn refresh(&self) -> Vec<Template> {
+        match self.imgflip.get_memes().await {
+            Ok(memes) => {
+                let templates: Vec<_> = memes
+                    .into_iter()
+                    .map(|meme| Template {
+                        id: meme.id().to_string(),
+                        name: meme.name().to_string(),
+                    })
+                    .collect();
+                info!(
+                    "refreshed template cache with {} templates",
+                    templates.len()
+                );
+                let mut state = self.state.write().await;
+                state.templates = templates.clone();
+                state.refreshed_at = Some(std::time::Instant::now());
+                templates
+            }
+            Err(error) => {
+                info!("failed to refresh template cache: {:?}", error);
+                self.state.read().await.templates.clone()
+            }
+        }
+    }
+}
+
+enum FuzzyResolution {
+    Match(String),
+    Candidates(Vec<String>),
+}
+
+const FUZZY_DISTANCE_THRESHOLD: usize = 3;
+const FUZZY_OVERLAP_THRESHOLD: f64 = 0.6;
+
+fn normalize_template_name(name: &str) -> String {
+    name.to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+        .collect()
+}
+
+fn token_overlap(a: &str, b: &str) -> f64 {
+    let a_tokens: std::collections::HashSet<_> = a.split_whitespace().collect();
+    let b_tokens: std::collections::HashSet<_> = b.split_whitespace().collect();
+    if a_tokens.is_empty() || b_tokens.is_empty() {
+        return 0.0;
+    }
+    let intersection = a_tokens.intersection(&b_tokens).count();
+    let union = a_tokens.union(&b_tokens).count();
+    intersection as f64 / union as f64
+}
+
+// `templates` is assumed to already be in imgflip's popularity order, so a
+// stable sort keeps ties resolved in favour of the more popular template.
+fn resolve_fuzzy(query: &str, templates: &[Template]) -> FuzzyResolution {
+    let normalized_query = normalize_template_name(query);
+
+    let mut scored: Vec<_> = templates
+        .iter()
+        .map(|template| {
+            let normalized_name = normalize_template_name(&template.name);
+            let distance = strsim::levenshtein(&normalized_query, &normalized_name);
+            let overlap = token_overlap(&normalized_query, &normalized_name);
+            (template, distance, overlap)
+        })
+        .collect();
+    scored.sort_by(|(_, d1, o1), (_, d2, o2)| d1.cmp(d2).then(o2.partial_cmp(o1).unwrap()));
+
+    match scored.first() {
+        Some((template, distance, overlap))
+            if *distance <= FUZZY_DISTANCE_THRESHOLD || *overlap >= FUZZY_OVERLAP_THRESHOLD =>
+        {
+            FuzzyResolution::Match(template.id.clone())
+        }
+        _ => FuzzyResolution::Candidates(
+            scored
+                .into_iter()
+                .take(3)
+                .map(|(template, _, _)| template.name.clone())
+                .collect(),
+        ),
+    }
+}
+
+fn candidates_response(slash_command: String, candidates: Vec<String>) -> Response {
+    let text = if candidates.is_empty() {
+        format!(
+            "No meme template matches that. Try `{}` with a known imgflip template name or ID.",
+            slash_command
+        )
+    } else {
+        format!(
+            "No meme template matches that. Did you mean: {}?",
+            candidates
+                .iter()
+                .map(|name| format!("`{}`", name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    };
+    let candidates_response = Response {
+        text: Some(text),
+        response_type: None,
+        username: None,
+        channel_id: None,
+        icon_url: Some(Url::parse("https://imgflip.com/imgflip_white_96.png").unwrap()),
+        goto_location: None,
+        skip_slack_parsing: Some(true),
+    };
+    info!(
+        "candidates response {:?}",
+        serde_json::to_string(&candidates_response)
+    );
+    candidates_response
+}
+
 fn usage(slash_command: String) -> Response {
     let usage_response = Response {
 		text: Some(format!("Usage: `{slash_command} <id>⇧⏎<text>⇧⏎…`\nExample:\n```{slash_command} 181913649\nmaking memes yourself\nusing a bot to make memes```", slash_command=slash_command)),
@@ -124,18 +498,91 @@ fn usage(slash_command: String) -> Response {
 
 async fn meme_reply(
     imgflip: std::sync::Arc<imgflip::AccountClient>,
+    meme_config: std::sync::Arc<MemeConfig>,
+    template_cache: std::sync::Arc<TemplateCache>,
+    alias_store: std::sync::Arc<AliasStore>,
     request: Request,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     info!("request: {:?}", request);
 
     let mut text_lines = request.text.lines();
-    let meme = match text_lines.next() {
-        Some(meme) => meme.to_string(),
+    let first_line = match text_lines.next() {
+        Some(first_line) => first_line,
         None => {
             let usage_response = usage(request.command);
             return Ok(warp::reply::json(&usage_response));
         }
     };
+
+    if let Some(command) = parse_alias_command(first_line) {
+        let response = match command {
+            AliasCommand::Add { keyword, id } => {
+                match alias_store.add(&request.team_id, keyword, id).await {
+                    Ok(()) => alias_response(format!("Added alias `{}` → `{}`.", keyword, id)),
+                    Err(error) => {
+                        info!("failed to add alias: {:?}", error);
+                        alias_response("Uhoh, something went wrong".to_string())
+                    }
+                }
+            }
+            AliasCommand::Del { keyword } => {
+                match alias_store.remove(&request.team_id, keyword).await {
+                    Ok(true) => alias_response(format!("Removed alias `{}`.", keyword)),
+                    Ok(false) => alias_response(format!("No alias `{}` registered.", keyword)),
+                    Err(error) => {
+                        info!("failed to remove alias: {:?}", error);
+                        alias_response("Uhoh, something went wrong".to_string())
+                    }
+                }
+            }
+            AliasCommand::List => {
+                let aliases = alias_store.list(&request.team_id);
+                let text = if aliases.is_empty() {
+                    "No aliases registered for this team.".to_string()
+                } else {
+                    aliases
+                        .iter()
+                        .map(|(keyword, id)| format!("`{}` → `{}`", keyword, id))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                };
+                alias_response(text)
+            }
+        };
+        return Ok(warp::reply::json(&response));
+    }
+
+    let selection = if let Some(id) = alias_store.resolve(&request.team_id, first_line) {
+        MemeSelection::Id(id)
+    } else if let Some(selection) = meme_config.resolve(first_line) {
+        selection
+    } else if first_line == RANDOM_KEYWORD {
+        MemeSelection::Random
+    } else if !first_line.is_empty() && first_line.chars().all(|c| c.is_ascii_digit()) {
+        MemeSelection::Id(first_line.to_string())
+    } else {
+        let templates = template_cache.templates().await;
+        match resolve_fuzzy(first_line, &templates) {
+            FuzzyResolution::Match(id) => MemeSelection::Id(id),
+            FuzzyResolution::Candidates(candidates) => {
+                let candidates_response = candidates_response(request.command, candidates);
+                return Ok(warp::reply::json(&candidates_response));
+            }
+        }
+    };
+    let meme = match selection {
+        MemeSelection::Id(id) => id,
+        MemeSelection::Random => {
+            let templates = template_cache.templates().await;
+            match templates.choose(&mut rand::thread_rng()) {
+                Some(template) => template.id.clone(),
+                None => {
+                    let candidates_response = candidates_response(request.command, Vec::new());
+                    return Ok(warp::reply::json(&candidates_response));
+                }
+            }
+        }
+    };
     let boxes: Vec<_> = text_lines.map(|s| s.to_string()).collect();
     if boxes.is_empty() {
         let usage_response = usage(request.command);
@@ -160,6 +607,15 @@ async fn meme_reply(
     Ok(warp::reply::json(&response))
 }
 
+/// The `imgflip` crate doesn't expose a typed rate-limit variant or code, only
+/// the free-text message from `imgflip::Error::ApiError`, so this heuristic is
+/// isolated here (and unit-tested against fixed sample messages) rather than
+/// inlined. It matches "too many requests" case-insensitively; any other
+/// wording is not treated as a rate limit.
+fn is_rate_limited_error(error_message: &str) -> bool {
+    error_message.to_lowercase().contains("too many requests")
+}
+
 async fn reply_with_meme(
     imgflip: std::sync::Arc<imgflip::AccountClient>,
     meme_request: MemeRequest,
@@ -185,9 +641,18 @@ async fn reply_with_meme(
             skip_slack_parsing: Some(true),
         },
         Err(error) => match error {
+            // imgflip surfaces rate-limiting and other API-level rejections as
+            // `ApiError`. A rate-limit rejection is posted to the whole channel
+            // (e.g. so a team notices their shared account ran out of
+            // captions); other API errors, like transport failures, are only
+            // shown to the requesting user.
             imgflip::Error::ApiError(error_message) => Response {
                 text: Some(format!("Uhoh, something went wrong: {}", error_message)),
-                response_type: None,
+                response_type: if is_rate_limited_error(&error_message) {
+                    Some("in_channel".to_string())
+                } else {
+                    None
+                },
                 username: None,
                 channel_id: None,
                 icon_url: Some(Url::parse("https://imgflip.com/imgflip_white_96.png").unwrap()),
@@ -208,12 +673,46 @@ async fn reply_with_meme(
     info!("user_response {:?}", user_response);
 
     let client = reqwest::Client::new();
-    let res = client
-        .post(response_url)
-        .json(&user_response)
-        .send()
-        .await
-        .unwrap();
+    deliver_response(&client, response_url, &user_response).await;
+}
+
+const RESPONSE_DELIVERY_ATTEMPTS: u32 = 4;
+const RESPONSE_DELIVERY_BASE_DELAY: std::time::Duration = std::time::Duration::from_millis(200);
+
+/// POSTs `response` to Mattermost's `response_url`, retrying transient
+/// failures with exponential backoff. Mattermost never retries a delayed
+/// response itself, so a user who hits a flaky network would otherwise never
+/// hear back; giving up after the last attempt is a no-op rather than a
+/// panic, since there's nothing left to report the failure to.
+async fn deliver_response(client: &reqwest::Client, response_url: Url, response: &Response) {
+    for attempt in 1..=RESPONSE_DELIVERY_ATTEMPTS {
+        let result = client
+            .post(response_url.clone())
+            .json(response)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status);
+        match result {
+            Ok(res) => {
+                info!("delivered response to {} ({})", response_url, res.status());
+                return;
+            }
+            Err(error) => {
+                log::warn!(
+                    "failed to deliver response to {} (attempt {}/{}): {:?}",
+                    response_url,
+                    attempt,
+                    RESPONSE_DELIVERY_ATTEMPTS,
+                    error
+                );
+                if attempt == RESPONSE_DELIVERY_ATTEMPTS {
+                    log::error!("giving up delivering response to {}", response_url);
+                    return;
+                }
+                tokio::time::sleep(RESPONSE_DELIVERY_BASE_DELAY * 2u32.pow(attempt - 1)).await;
+            }
+        }
+    }
 }
 
 fn with_imgflip(
@@ -223,6 +722,27 @@ fn with_imgflip(
     warp::any().map(move || imgflip.clone())
 }
 
+fn with_meme_config(
+    meme_config: std::sync::Arc<MemeConfig>,
+) -> impl Filter<Extract = (std::sync::Arc<MemeConfig>,), Error = std::convert::Infallible> + Clone
+{
+    warp::any().map(move || meme_config.clone())
+}
+
+fn with_template_cache(
+    template_cache: std::sync::Arc<TemplateCache>,
+) -> impl Filter<Extract = (std::sync::Arc<TemplateCache>,), Error = std::convert::Infallible> + Clone
+{
+    warp::any().map(move || template_cache.clone())
+}
+
+fn with_alias_store(
+    alias_store: std::sync::Arc<AliasStore>,
+) -> impl Filter<Extract = (std::sync::Arc<AliasStore>,), Error = std::convert::Infallible> + Clone
+{
+    warp::any().map(move || alias_store.clone())
+}
+
 /// Mattermost slash command for api.imgflip.com
 ///
 /// HTTP server for a custom Mattermost slash command that creates memes via api.imgflip.com
@@ -241,6 +761,20 @@ struct Cli {
     /// Token(s) of the allowed slash command requests
     #[clap(required = true, short = "T", long, env, hide_env_values = true)]
     slash_command_token: Vec<String>,
+
+    /// Path to a TOML file mapping meme keywords to imgflip template IDs
+    #[clap(long, env)]
+    meme_config: Option<std::path::PathBuf>,
+
+    /// Where to read the slash command token from: `header` (default,
+    /// requires a reverse proxy to inject `Authorization: Token <token>`) or
+    /// `body` (validate Mattermost's native `token` form field directly)
+    #[clap(long, env, default_value = "header")]
+    token_source: TokenSource,
+
+    /// Directory for the sled database of user-registered meme aliases
+    #[clap(long, env, default_value = "meme-aliases.sled")]
+    store_path: std::path::PathBuf,
 }
 
 mod problem {
@@ -309,10 +843,22 @@ async fn main() -> anyhow::Result<()> {
         args.imgflip_password,
     ));
     let tokens = args.slash_command_token;
+    let token_source = args.token_source;
     let socket_addr: std::net::SocketAddr = args.socket_addr.into();
 
+    let meme_config = match &args.meme_config {
+        Some(path) => MemeConfig::load(path)?,
+        None => MemeConfig::default(),
+    };
+    let meme_config = std::sync::Arc::new(meme_config);
+    let template_cache = std::sync::Arc::new(TemplateCache::new(imgflip.clone()));
+    let alias_store = std::sync::Arc::new(AliasStore::open(&args.store_path)?);
+
     let hook = with_imgflip(imgflip)
-        .and(webhook(move |request_token| {
+        .and(with_meme_config(meme_config))
+        .and(with_template_cache(template_cache))
+        .and(with_alias_store(alias_store))
+        .and(webhook(token_source, move |request_token| {
             tokens
                 .iter()
                 .any(|configured_token| configured_token == request_token)
@@ -325,3 +871,324 @@ async fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod fuzzy_tests {
+    use super::*;
+
+    fn template(id: &str, name: &str) -> Template {
+        Template {
+            id: id.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn token_overlap_is_one_for_identical_names() {
+        assert_eq!(token_overlap("two buttons", "two buttons"), 1.0);
+    }
+
+    #[test]
+    fn token_overlap_is_zero_without_shared_tokens() {
+        assert_eq!(token_overlap("drake", "success kid"), 0.0);
+    }
+
+    #[test]
+    fn token_overlap_is_partial_for_shared_tokens() {
+        assert_eq!(token_overlap("two buttons", "two buttons meme"), 2.0 / 3.0);
+    }
+
+    #[test]
+    fn normalize_template_name_lowercases_and_strips_punctuation() {
+        assert_eq!(
+            normalize_template_name("Distracted, Boyfriend!"),
+            "distracted boyfriend"
+        );
+    }
+
+    #[test]
+    fn resolve_fuzzy_matches_within_the_distance_threshold() {
+        let templates = vec![template("112126428", "Distracted Boyfriend")];
+        match resolve_fuzzy("distracted boyfrend", &templates) {
+            FuzzyResolution::Match(id) => assert_eq!(id, "112126428"),
+            FuzzyResolution::Candidates(_) => panic!("expected a match"),
+        }
+    }
+
+    #[test]
+    fn resolve_fuzzy_rejects_beyond_the_distance_and_overlap_thresholds() {
+        let templates = vec![template("112126428", "Distracted Boyfriend")];
+        match resolve_fuzzy("completely unrelated words", &templates) {
+            FuzzyResolution::Match(_) => panic!("expected no match"),
+            FuzzyResolution::Candidates(candidates) => {
+                assert_eq!(candidates, vec!["Distracted Boyfriend".to_string()])
+            }
+        }
+    }
+
+    #[test]
+    fn resolve_fuzzy_breaks_ties_by_popularity_order() {
+        // Both templates are equidistant from the query; `templates` is in
+        // imgflip popularity order, so the stable sort should keep the first.
+        let templates = vec![
+            template("1", "One Does Not Simply"),
+            template("2", "One Does Not Simply"),
+        ];
+        match resolve_fuzzy("one does not simply", &templates) {
+            FuzzyResolution::Match(id) => assert_eq!(id, "1"),
+            FuzzyResolution::Candidates(_) => panic!("expected a match"),
+        }
+    }
+
+    #[test]
+    fn resolve_fuzzy_truncates_candidates_to_three() {
+        let templates = vec![
+            template("1", "aaaaaaaaaa"),
+            template("2", "bbbbbbbbbb"),
+            template("3", "cccccccccc"),
+            template("4", "dddddddddd"),
+        ];
+        match resolve_fuzzy("zzzzzzzzzz", &templates) {
+            FuzzyResolution::Match(_) => panic!("expected no match"),
+            FuzzyResolution::Candidates(candidates) => assert_eq!(candidates.len(), 3),
+        }
+    }
+}
+
+#[cfg(test)]
+mod delivery_tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn is_rate_limited_error_matches_too_many_requests() {
+        assert!(is_rate_limited_error("Too Many Requests"));
+        assert!(!is_rate_limited_error("invalid username or password"));
+    }
+
+    #[tokio::test]
+    async fn deliver_response_retries_on_non_success_status() {
+        let attempts = std::sync::Arc::new(AtomicUsize::new(0));
+        let counted = attempts.clone();
+        let route = warp::post().map(move || {
+            counted.fetch_add(1, Ordering::SeqCst);
+            warp::reply::with_status(
+                warp::reply::reply(),
+                warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        });
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let response_url = Url::parse(&format!("http://{}/", addr)).unwrap();
+        let client = reqwest::Client::new();
+        let response = Response {
+            text: None,
+            response_type: None,
+            username: None,
+            channel_id: None,
+            icon_url: None,
+            goto_location: None,
+            skip_slack_parsing: None,
+        };
+
+        deliver_response(&client, response_url, &response).await;
+
+        assert_eq!(
+            attempts.load(Ordering::SeqCst),
+            RESPONSE_DELIVERY_ATTEMPTS as usize
+        );
+    }
+}
+
+#[cfg(test)]
+mod alias_tests {
+    use super::*;
+
+    fn temp_store() -> (tempfile::TempDir, AliasStore) {
+        let dir = tempfile::tempdir().unwrap();
+        let store = AliasStore::open(dir.path()).unwrap();
+        (dir, store)
+    }
+
+    #[tokio::test]
+    async fn add_resolve_list_and_remove_round_trip() {
+        let (_dir, store) = temp_store();
+
+        assert_eq!(store.resolve("team-1", "fresse"), None);
+
+        store.add("team-1", "fresse", "375").await.unwrap();
+        assert_eq!(store.resolve("team-1", "fresse"), Some("375".to_string()));
+        assert_eq!(
+            store.list("team-1"),
+            vec![("fresse".to_string(), "375".to_string())]
+        );
+
+        assert!(store.remove("team-1", "fresse").await.unwrap());
+        assert_eq!(store.resolve("team-1", "fresse"), None);
+        assert!(store.list("team-1").is_empty());
+    }
+
+    #[tokio::test]
+    async fn removing_an_unknown_alias_returns_false() {
+        let (_dir, store) = temp_store();
+        assert!(!store.remove("team-1", "nope").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn aliases_are_isolated_per_team() {
+        let (_dir, store) = temp_store();
+
+        // `"foo/bar"` would collide with `"foo"` under a naive
+        // `"{team_id}/{keyword}"` prefix scan; per-team sled trees must keep
+        // them apart.
+        store.add("foo", "fresse", "1").await.unwrap();
+        store.add("foo/bar", "fresse", "2").await.unwrap();
+
+        assert_eq!(store.resolve("foo", "fresse"), Some("1".to_string()));
+        assert_eq!(store.resolve("foo/bar", "fresse"), Some("2".to_string()));
+        assert_eq!(
+            store.list("foo"),
+            vec![("fresse".to_string(), "1".to_string())]
+        );
+        assert_eq!(
+            store.list("foo/bar"),
+            vec![("fresse".to_string(), "2".to_string())]
+        );
+    }
+
+    #[test]
+    fn parse_alias_command_parses_add_with_keyword_and_id() {
+        assert!(matches!(
+            parse_alias_command("alias add fresse 375"),
+            Some(AliasCommand::Add {
+                keyword: "fresse",
+                id: "375"
+            })
+        ));
+    }
+
+    #[test]
+    fn parse_alias_command_rejects_add_missing_id() {
+        assert!(parse_alias_command("alias add fresse").is_none());
+    }
+
+    #[test]
+    fn parse_alias_command_rejects_add_missing_keyword_and_id() {
+        assert!(parse_alias_command("alias add").is_none());
+    }
+
+    #[test]
+    fn parse_alias_command_parses_del_with_keyword() {
+        assert!(matches!(
+            parse_alias_command("alias del fresse"),
+            Some(AliasCommand::Del { keyword: "fresse" })
+        ));
+    }
+
+    #[test]
+    fn parse_alias_command_rejects_del_missing_keyword() {
+        assert!(parse_alias_command("alias del").is_none());
+    }
+
+    #[test]
+    fn parse_alias_command_parses_list() {
+        assert!(matches!(
+            parse_alias_command("alias list"),
+            Some(AliasCommand::List)
+        ));
+    }
+
+    #[test]
+    fn parse_alias_command_rejects_unknown_subcommand() {
+        assert!(parse_alias_command("alias frobnicate").is_none());
+    }
+
+    #[test]
+    fn parse_alias_command_rejects_non_alias_input() {
+        assert!(parse_alias_command("181913649").is_none());
+        assert!(parse_alias_command("").is_none());
+    }
+}
+
+#[cfg(test)]
+mod webhook_tests {
+    use super::*;
+
+    fn token_validator(
+        tokens: &'static [&'static str],
+    ) -> impl Fn(&str) -> bool + Clone + Send + Sync + 'static {
+        move |token: &str| tokens.contains(&token)
+    }
+
+    fn sample_form_body(token: &str) -> String {
+        let pairs = [
+            ("channel_id", "channel-id"),
+            ("channel_name", "town-square"),
+            ("command", "/meme"),
+            ("response_url", "https://mattermost.example/response"),
+            ("team_domain", "team"),
+            ("team_id", "team-id"),
+            ("text", "181913649\nhello"),
+            ("token", token),
+            ("trigger_id", "trigger-id"),
+            ("user_id", "user-id"),
+            ("user_name", "user"),
+        ];
+        url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(pairs.iter())
+            .finish()
+    }
+
+    #[tokio::test]
+    async fn header_token_source_accepts_a_valid_token() {
+        let filter = webhook(TokenSource::Header, token_validator(&["good-token"]));
+        let request: Request = warp::test::request()
+            .method("POST")
+            .header("authorization", "Token good-token")
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(sample_form_body("ignored"))
+            .filter(&filter)
+            .await
+            .expect("a valid header token should be accepted");
+        assert_eq!(request.team_id, "team-id");
+    }
+
+    #[tokio::test]
+    async fn header_token_source_rejects_an_invalid_token() {
+        let filter = webhook(TokenSource::Header, token_validator(&["good-token"]));
+        let result = warp::test::request()
+            .method("POST")
+            .header("authorization", "Token bad-token")
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(sample_form_body("ignored"))
+            .filter(&filter)
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn body_token_source_accepts_a_valid_token() {
+        let filter = webhook(TokenSource::Body, token_validator(&["good-token"]));
+        let request: Request = warp::test::request()
+            .method("POST")
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(sample_form_body("good-token"))
+            .filter(&filter)
+            .await
+            .expect("a valid body token should be accepted");
+        assert_eq!(request.team_id, "team-id");
+    }
+
+    #[tokio::test]
+    async fn body_token_source_rejects_an_invalid_token() {
+        let filter = webhook(TokenSource::Body, token_validator(&["good-token"]));
+        let result = warp::test::request()
+            .method("POST")
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(sample_form_body("bad-token"))
+            .filter(&filter)
+            .await;
+        assert!(result.is_err());
+    }
+}